@@ -1,77 +1,407 @@
+use std::cmp::Ordering;
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Copy)]
-pub(crate) struct Frac {
-    num: usize,
-    den: usize,
+/// A non-negative integer, wide enough to track a [Frac]'s numerator and denominator through
+/// arbitrarily deep or unbalanced split trees without overflowing.
+///
+/// Most fractions stay small (the numerator/denominator for a balanced split tree of depth `d`
+/// fits in `d + 1` bits), so [Num] stays inline as a `u128` as long as it can and only spills over
+/// to a heap-allocated limb vector once an operation would no longer fit.
+#[derive(Debug, Clone)]
+enum Num {
+    Small(u128),
+    Big(Vec<u64>),
 }
 
-impl Frac {
-    pub(crate) fn new() -> Self {
-        Frac { num: 1, den: 1 }
+fn trim(limbs: &mut Vec<u64>) {
+    while limbs.len() > 1 && *limbs.last().unwrap() == 0 {
+        limbs.pop();
+    }
+}
+
+fn cmp_limbs(a: &[u64], b: &[u64]) -> Ordering {
+    if a.len() != b.len() {
+        return a.len().cmp(&b.len());
+    }
+    for i in (0..a.len()).rev() {
+        if a[i] != b[i] {
+            return a[i].cmp(&b[i]);
+        }
     }
+    Ordering::Equal
+}
 
-    pub(crate) fn split(&mut self) -> Result<Self, SplitErr> {
-        self.den = self.den.checked_add(1).ok_or(SplitErr())?;
-        Ok(Self {
-            num: self.num,
-            den: self.den,
-        })
+fn add_limbs(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let len = a.len().max(b.len());
+    let mut result = Vec::with_capacity(len + 1);
+    let mut carry = 0u64;
+    for i in 0..len {
+        let x = a.get(i).copied().unwrap_or(0);
+        let y = b.get(i).copied().unwrap_or(0);
+        let (sum, overflow1) = x.overflowing_add(y);
+        let (sum, overflow2) = sum.overflowing_add(carry);
+        result.push(sum);
+        carry = u64::from(overflow1) + u64::from(overflow2);
+    }
+    if carry > 0 {
+        result.push(carry);
     }
+    trim(&mut result);
+    result
+}
+
+/// Subtracts `b` from `a`. Callers must ensure `a >= b`.
+fn sub_limbs(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = false;
+    for (i, &x) in a.iter().enumerate() {
+        let y = b.get(i).copied().unwrap_or(0);
+        let (diff, borrow1) = x.overflowing_sub(y);
+        let (diff, borrow2) = diff.overflowing_sub(u64::from(borrow));
+        result.push(diff);
+        borrow = borrow1 || borrow2;
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_limbs(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry: u128 = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let idx = i + j;
+            let prod = u128::from(x) * u128::from(y) + u128::from(result[idx]) + carry;
+            result[idx] = prod as u64;
+            carry = prod >> 64;
+        }
+        let mut idx = i + b.len();
+        while carry > 0 {
+            let sum = u128::from(result[idx]) + carry;
+            result[idx] = sum as u64;
+            carry = sum >> 64;
+            idx += 1;
+        }
+    }
+    trim(&mut result);
+    result
+}
+
+/// Schoolbook binary long division, since it only needs the shift/add/sub/cmp primitives we
+/// already have, with no separate bignum-division algorithm to maintain.
+fn divmod_limbs(a: &[u64], b: &[u64]) -> (Vec<u64>, Vec<u64>) {
+    if cmp_limbs(a, b) == Ordering::Less {
+        return (vec![0], a.to_vec());
+    }
+
+    let total_bits = a.len() * 64;
+    let mut quotient = vec![0u64; a.len()];
+    let mut remainder: Vec<u64> = vec![0];
+
+    for bit in (0..total_bits).rev() {
+        remainder = shl_limbs(&remainder, 1);
+        let limb_idx = bit / 64;
+        let bit_idx = bit % 64;
+        if (a[limb_idx] >> bit_idx) & 1 == 1 {
+            remainder = add_limbs(&remainder, &[1]);
+        }
+        if cmp_limbs(&remainder, b) != Ordering::Less {
+            remainder = sub_limbs(&remainder, b);
+            quotient[limb_idx] |= 1 << bit_idx;
+        }
+    }
+
+    trim(&mut quotient);
+    trim(&mut remainder);
+    (quotient, remainder)
+}
+
+fn shl_limbs(limbs: &[u64], diff: usize) -> Vec<u64> {
+    let limb_shift = diff / 64;
+    let bit_shift = diff % 64;
+    let mut result = vec![0u64; limbs.len() + limb_shift + 1];
+    for (i, &limb) in limbs.iter().enumerate() {
+        let idx = i + limb_shift;
+        if bit_shift == 0 {
+            result[idx] |= limb;
+        } else {
+            result[idx] |= limb << bit_shift;
+            result[idx + 1] |= limb >> (64 - bit_shift);
+        }
+    }
+    trim(&mut result);
+    result
+}
 
-    pub(crate) fn merge(&mut self, mut other: Self) -> Result<(), MergeErr> {
-        let (min_den, max_den) = if self.den < other.den {
-            (&mut *self, &mut other)
+fn shr_limbs(limbs: &[u64], n: usize) -> Vec<u64> {
+    let limb_shift = n / 64;
+    let bit_shift = n % 64;
+    if limb_shift >= limbs.len() {
+        return vec![0];
+    }
+    let src = &limbs[limb_shift..];
+    let mut result = vec![0u64; src.len()];
+    for i in 0..src.len() {
+        let lo = src[i] >> bit_shift;
+        let hi = if bit_shift == 0 || i + 1 >= src.len() {
+            0
         } else {
-            (&mut other, &mut *self)
+            src[i + 1] << (64 - bit_shift)
         };
+        result[i] = lo | hi;
+    }
+    trim(&mut result);
+    result
+}
 
-        let diff = max_den.den - min_den.den;
-        min_den.num = min_den
-            .num
-            .checked_shl(diff.try_into().map_err(|_| MergeErr())?)
-            .ok_or(MergeErr())?;
-        let mut num = min_den.num + max_den.num;
-        let trailing = num.trailing_zeros() as usize;
-        num >>= trailing;
-        let den = max_den.den - trailing;
+fn trailing_zeros_limbs(limbs: &[u64]) -> usize {
+    let mut total = 0;
+    for &limb in limbs {
+        if limb == 0 {
+            total += 64;
+        } else {
+            return total + limb.trailing_zeros() as usize;
+        }
+    }
+    total
+}
 
-        self.num = num;
-        self.den = den;
-        Ok(())
+impl Num {
+    fn one() -> Self {
+        Num::Small(1)
     }
 
-    pub(crate) fn is_one(&self) -> bool {
-        self.num == 1 && self.den == 1
+    fn from_usize(n: usize) -> Self {
+        Num::Small(n as u128)
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Num::Small(n) => *n == 0,
+            Num::Big(limbs) => limbs.iter().all(|&limb| limb == 0),
+        }
+    }
+
+    fn is_one(&self) -> bool {
+        matches!(self, Num::Small(1))
+    }
+
+    fn as_limbs(&self) -> Vec<u64> {
+        match self {
+            Num::Small(n) => {
+                let mut limbs = vec![*n as u64, (*n >> 64) as u64];
+                trim(&mut limbs);
+                limbs
+            }
+            Num::Big(limbs) => limbs.clone(),
+        }
+    }
+
+    fn from_limbs(limbs: Vec<u64>) -> Self {
+        if limbs.len() <= 2 {
+            let mut n: u128 = 0;
+            for (i, &limb) in limbs.iter().enumerate() {
+                n |= u128::from(limb) << (64 * i);
+            }
+            Num::Small(n)
+        } else {
+            Num::Big(limbs)
+        }
+    }
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        if let (Num::Small(a), Num::Small(b)) = (self, other) {
+            return a.cmp(b);
+        }
+        cmp_limbs(&self.as_limbs(), &other.as_limbs())
+    }
+
+    fn shl(&self, diff: usize) -> Self {
+        if let Num::Small(n) = self {
+            if diff < 128 && n.leading_zeros() >= diff as u32 {
+                return Num::Small(n << diff);
+            }
+        }
+        Num::from_limbs(shl_limbs(&self.as_limbs(), diff))
+    }
+
+    fn shr(&self, n: usize) -> Self {
+        if let Num::Small(v) = self {
+            return Num::Small(if n >= 128 { 0 } else { v >> n });
+        }
+        Num::from_limbs(shr_limbs(&self.as_limbs(), n))
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        if let (Num::Small(a), Num::Small(b)) = (self, other) {
+            if let Some(sum) = a.checked_add(*b) {
+                return Num::Small(sum);
+            }
+        }
+        Num::from_limbs(add_limbs(&self.as_limbs(), &other.as_limbs()))
+    }
+
+    /// Subtracts `other` from `self`. Callers must ensure `self >= other`.
+    fn sub(&self, other: &Self) -> Self {
+        if let (Num::Small(a), Num::Small(b)) = (self, other) {
+            return Num::Small(a - b);
+        }
+        Num::from_limbs(sub_limbs(&self.as_limbs(), &other.as_limbs()))
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        if let (Num::Small(a), Num::Small(b)) = (self, other) {
+            if let Some(prod) = a.checked_mul(*b) {
+                return Num::Small(prod);
+            }
+        }
+        Num::from_limbs(mul_limbs(&self.as_limbs(), &other.as_limbs()))
+    }
+
+    fn divmod(&self, other: &Self) -> (Self, Self) {
+        if let (Num::Small(a), Num::Small(b)) = (self, other) {
+            return (Num::Small(a / b), Num::Small(a % b));
+        }
+        let (q, r) = divmod_limbs(&self.as_limbs(), &other.as_limbs());
+        (Num::from_limbs(q), Num::from_limbs(r))
+    }
+
+    fn div_exact(&self, other: &Self) -> Self {
+        self.divmod(other).0
+    }
+
+    fn trailing_zeros(&self) -> usize {
+        match self {
+            Num::Small(0) => 0,
+            Num::Small(n) => n.trailing_zeros() as usize,
+            Num::Big(limbs) => trailing_zeros_limbs(limbs),
+        }
+    }
+
+    /// Binary GCD: only needs shifts, subtraction and comparison, so it reuses the same
+    /// primitives as the rest of [Num] instead of pulling in a division-based algorithm.
+    fn gcd(&self, other: &Self) -> Self {
+        if self.is_zero() {
+            return other.clone();
+        }
+        if other.is_zero() {
+            return self.clone();
+        }
+
+        let mut a = self.clone();
+        let mut b = other.clone();
+        let shift = a.trailing_zeros().min(b.trailing_zeros());
+        a = a.shr(a.trailing_zeros());
+
+        loop {
+            b = b.shr(b.trailing_zeros());
+            if a.cmp(&b) == Ordering::Greater {
+                std::mem::swap(&mut a, &mut b);
+            }
+            b = b.sub(&a);
+            if b.is_zero() {
+                break;
+            }
+        }
+
+        a.shl(shift)
     }
 }
 
-impl std::fmt::Display for Frac {
+impl Display for Num {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}/((2^{}) - 1)", self.num, self.den)
+        match self {
+            Num::Small(n) => write!(f, "{n}"),
+            Num::Big(_) => {
+                let ten = Num::from_usize(10);
+                let mut n = self.clone();
+                let mut digits = Vec::new();
+                while !n.is_zero() {
+                    let (q, r) = n.divmod(&ten);
+                    let digit = match r {
+                        Num::Small(d) => d as u8,
+                        Num::Big(_) => 0,
+                    };
+                    digits.push(b'0' + digit);
+                    n = q;
+                }
+                if digits.is_empty() {
+                    digits.push(b'0');
+                }
+                digits.reverse();
+                f.write_str(std::str::from_utf8(&digits).unwrap())
+            }
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct MergeErr();
+/// A reduced rational `num / den` with invariant `0 < num/den <= 1`, normalized by dividing out
+/// `gcd(num, den)` after every operation.
+#[derive(Debug, Clone)]
+pub(crate) struct Frac {
+    num: Num,
+    den: Num,
+}
 
-impl Display for MergeErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Nominator became too big, could not merge!")
+impl Frac {
+    pub(crate) fn new() -> Self {
+        Frac {
+            num: Num::one(),
+            den: Num::one(),
+        }
     }
-}
 
-impl std::error::Error for MergeErr {}
+    fn reduce(&mut self) {
+        let gcd = self.num.gcd(&self.den);
+        if !gcd.is_one() {
+            self.num = self.num.div_exact(&gcd);
+            self.den = self.den.div_exact(&gcd);
+        }
+    }
+
+    /// Splits the current share into `k` equal parts, mutating `self` into one of them and
+    /// returning the other `k - 1` as new [Frac]s.
+    pub(crate) fn split_n(&mut self, k: usize) -> Vec<Self> {
+        assert!(k > 0, "cannot split into zero parts");
+        self.den = self.den.mul(&Num::from_usize(k));
+        self.reduce();
+        (1..k).map(|_| self.clone()).collect()
+    }
 
-#[derive(Debug)]
-pub struct SplitErr();
+    /// Splits off a single equal share of `self`. The `k = 2` special case of [`Self::split_n`].
+    pub(crate) fn split(&mut self) -> Self {
+        self.split_n(2)
+            .pop()
+            .expect("split_n(2) always yields one share")
+    }
 
-impl Display for SplitErr {
+    /// Merges `other`'s ownership into `self`, i.e. `self += other`.
+    pub(crate) fn merge(&mut self, other: Self) {
+        let num = self.num.mul(&other.den).add(&other.num.mul(&self.den));
+        let den = self.den.mul(&other.den);
+        self.num = num;
+        self.den = den;
+        self.reduce();
+    }
+
+    /// Folds every [Frac] in `others` back into `self`. The many-at-once counterpart to
+    /// [`Self::merge`].
+    pub(crate) fn merge_all(&mut self, others: impl IntoIterator<Item = Self>) {
+        for other in others {
+            self.merge(other);
+        }
+    }
+
+    pub(crate) fn is_one(&self) -> bool {
+        self.num.cmp(&self.den) == Ordering::Equal
+    }
+}
+
+impl std::fmt::Display for Frac {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Denominater was about to overflow, could not split!")
+        write!(f, "{}/{}", self.num, self.den)
     }
 }
-impl std::error::Error for SplitErr {}
 
 #[cfg(test)]
 mod tests {
@@ -86,11 +416,58 @@ mod tests {
     #[test]
     fn split_seems_reasonable() {
         let mut three_fourth = Frac::new();
-        let mut half = three_fourth.split().unwrap();
-        let quater = half.split().unwrap();
-        three_fourth.merge(quater).unwrap();
+        let mut half = three_fourth.split();
+        let quater = half.split();
+        three_fourth.merge(quater);
+
+        assert!(matches!(three_fourth.num, Num::Small(3)));
+        assert!(matches!(three_fourth.den, Num::Small(4)));
+    }
+
+    #[test]
+    fn split_n_divides_into_equal_parts() {
+        let mut whole = Frac::new();
+        let thirds = whole.split_n(3);
+        assert_eq!(2, thirds.len());
 
-        assert_eq!(3, three_fourth.num);
-        assert_eq!(4, 1 << (three_fourth.den - 1));
+        whole.merge_all(thirds);
+
+        assert!(whole.is_one());
+    }
+
+    #[test]
+    fn deep_split_n_tree_round_trips_with_odd_fanout() {
+        // A purely binary split tree only ever produces GCDs whose odd part is 1, so it can't
+        // catch a `shl` that corrupts a large odd factor. Fan out with an odd `k` instead, deep
+        // enough to force `Num::Big`, and check the round trip still recovers a whole `Frac`.
+        const K: usize = 3;
+        let mut frac = Frac::new();
+        let mut current = frac.split();
+        let mut leaves = Vec::new();
+        for _ in 0..100 {
+            leaves.extend(current.split_n(K));
+        }
+        leaves.push(current);
+
+        frac.merge_all(leaves);
+
+        assert!(frac.is_one());
+    }
+
+    #[test]
+    fn deep_split_tree_does_not_overflow() {
+        // 150 levels deep exceeds both `usize` (64 bits) and `u128` (128 bits), forcing the
+        // numerator and denominator onto the heap-backed `Num::Big` path and back.
+        let mut frac = Frac::new();
+        let mut current = frac.split();
+        let mut leaves = Vec::new();
+        for _ in 0..150 {
+            leaves.push(current.split());
+        }
+        leaves.push(current);
+
+        frac.merge_all(leaves);
+
+        assert!(frac.is_one());
     }
 }
@@ -1,8 +1,22 @@
+// Unsized coercion (`Frc<[T; N]> -> Frc<[T]>`, `Frc<Concrete> -> Frc<dyn Trait>`) needs the
+// unstable `CoerceUnsized` trait, so this crate requires nightly.
+//
+// `DispatchFromDyn` is deliberately not implemented: it requires every field besides the
+// coerced pointer to be a 1-byte ZST, but `frac: Frac` lives inline next to `item`, so `Frc`
+// can never satisfy it. `Frc<dyn Trait>` is still fully usable (constructed, deref'd, merged,
+// split, ...) via `CoerceUnsized` alone; it just can't be used as a `self: Frc<Self>` receiver.
+#![feature(coerce_unsized, unsize)]
+
 mod frac;
 
 use frac::Frac;
 
-use std::{fmt::Display, marker::PhantomData, ops::Deref, ptr::NonNull};
+use std::{
+    fmt::Display,
+    marker::{PhantomData, Unsize},
+    ops::{CoerceUnsized, Deref},
+    ptr::NonNull,
+};
 
 #[derive(Debug)]
 pub struct Frc<T: ?Sized> {
@@ -12,6 +26,7 @@ pub struct Frc<T: ?Sized> {
 }
 
 unsafe impl<T: Send + Sync> Send for Frc<T> where T: ?Sized {}
+unsafe impl<T: Send + Sync> Sync for Frc<T> where T: ?Sized {}
 
 impl<T: ?Sized> Frc<T> {
     unsafe fn from_inner(ptr: NonNull<T>) -> Self {
@@ -108,6 +123,58 @@ impl<T> Frc<T> {
     }
 }
 
+impl<T: Clone> Frc<T> {
+    /// Returns a mutable reference to the inner value, cloning it into a fresh, fully-owned
+    /// [Frc] first if ownership is currently split. The other shares are left pointing at the
+    /// original, untouched data.
+    ///
+    /// # Examples
+    /// ```
+    /// use frc::Frc;
+    ///
+    /// let mut first = Frc::new(vec![1, 2, 3]);
+    /// let mut split = first.split();
+    ///
+    /// split.make_mut().push(4);
+    ///
+    /// assert_eq!(*split, vec![1, 2, 3, 4]);
+    /// assert_eq!(*first, vec![1, 2, 3]);
+    /// ```
+    pub fn make_mut(&mut self) -> &mut T {
+        if !self.frac.is_one() {
+            *self = Frc::new(T::clone(self));
+        }
+        unsafe { self.item.as_mut() }
+    }
+}
+
+impl<T: ?Sized> Frc<T> {
+    /// Returns a mutable reference to the inner value if `self` has whole ownership, i.e. no
+    /// other [Frc] holds a split sharing the same data. Returns `None` otherwise, since a
+    /// unique mutable borrow would not be sound while shares exist.
+    ///
+    /// # Examples
+    /// ```
+    /// use frc::Frc;
+    ///
+    /// let mut first = Frc::new(8);
+    /// assert!(first.get_mut().is_some());
+    ///
+    /// let mut split = first.split();
+    /// assert!(first.get_mut().is_none());
+    ///
+    /// split.merge(first);
+    /// assert!(split.get_mut().is_some());
+    /// ```
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.frac.is_one() {
+            Some(unsafe { self.item.as_mut() })
+        } else {
+            None
+        }
+    }
+}
+
 impl<T: ?Sized> Frc<T> {
     /// Creates a [Frc], distributing the ownership of the input between itself and the new [Frc].
     ///
@@ -120,13 +187,10 @@ impl<T: ?Sized> Frc<T> {
     /// assert_eq!(*first, *second);
     /// ```
     ///
-    /// # Panics
-    ///
-    /// If (2^64) subsplits are made.
     pub fn split(&mut self) -> Self {
         Self {
             item: self.item,
-            frac: self.frac.split().unwrap(),
+            frac: self.frac.split(),
             phantom: self.phantom,
         }
     }
@@ -148,12 +212,10 @@ impl<T: ?Sized> Frc<T> {
     /// ```
     ///
     /// # Panics
-    /// If an unrelated [Frc] was about to be merged, or if the backing fraction overflows.
+    /// If an unrelated [Frc] was about to be merged.
     pub fn merge(&mut self, other: Self) {
-        assert!(self.item == other.item);
-        if let Err(e) = unsafe { self.merge_unchecked(other) } {
-            panic!("Failed merging Frcs: {}", e);
-        }
+        assert!(std::ptr::addr_eq(self.item.as_ptr(), other.item.as_ptr()));
+        unsafe { self.merge_unchecked(other) };
     }
 
     /// Merges two [Frc]s, adding their partial ownerships together. Returns [MergeErr] if the
@@ -176,23 +238,122 @@ impl<T: ?Sized> Frc<T> {
     /// assert_eq!(second.unwrap(), vec![1234]);
     /// ```
     pub fn try_merge(&mut self, other: Self) -> Result<(), MergeErr<T>> {
-        if self.item == other.item {
-            unsafe { self.merge_unchecked(other) }?;
+        if std::ptr::addr_eq(self.item.as_ptr(), other.item.as_ptr()) {
+            unsafe { self.merge_unchecked(other) };
             Ok(())
         } else {
-            Err(MergeErr {
-                kind: MergeErrKind::IncompatibleFrcs,
-                other,
-            })
+            Err(MergeErr { other })
         }
     }
 
-    pub unsafe fn merge_unchecked(&mut self, other: Self) -> Result<(), MergeErr<T>> {
-        self.frac.merge(other.frac).map_err(|_| MergeErr {
-            kind: MergeErrKind::FractionOverflow(FracOverflowInfo(self.frac, other.frac)),
-            other,
-        })?;
-        Ok(())
+    /// Merges two [Frc]s without checking that they share the same allocation.
+    ///
+    /// # Safety
+    /// `other` must own a share of the same allocation as `self`.
+    pub unsafe fn merge_unchecked(&mut self, other: Self) {
+        self.frac.merge(other.frac);
+    }
+
+    /// Splits `self` into `k` equal shares, mutating `self` into one of them and returning the
+    /// other `k - 1` as a [Vec]. The `k`-ary generalization of [`Frc::split`], letting ownership
+    /// be divided into, say, thirds in a single step instead of through nested halving.
+    ///
+    /// # Examples
+    /// ```
+    /// use frc::Frc;
+    ///
+    /// let mut first = Frc::new(8);
+    /// let rest = first.split_n(3);
+    /// assert_eq!(2, rest.len());
+    /// ```
+    ///
+    /// # Panics
+    /// If `k` is `0`.
+    pub fn split_n(&mut self, k: usize) -> Vec<Self> {
+        self.frac
+            .split_n(k)
+            .into_iter()
+            .map(|frac| Self {
+                item: self.item,
+                frac,
+                phantom: self.phantom,
+            })
+            .collect()
+    }
+
+    /// Merges every [Frc] in `others` back into `self` in turn. The many-at-once counterpart to
+    /// [`Frc::merge`].
+    ///
+    /// # Examples
+    /// ```
+    /// use frc::Frc;
+    ///
+    /// let mut first = Frc::new(8);
+    /// let rest = first.split_n(3);
+    ///
+    /// first.merge_all(rest);
+    ///
+    /// assert_eq!(first.unwrap(), 8);
+    /// ```
+    ///
+    /// # Panics
+    /// If any [Frc] in `others` is unrelated to `self`.
+    pub fn merge_all(&mut self, others: impl IntoIterator<Item = Self>) {
+        let item = self.item;
+        self.frac.merge_all(others.into_iter().map(|other| {
+            assert!(std::ptr::addr_eq(item.as_ptr(), other.item.as_ptr()));
+            other.frac
+        }));
+    }
+
+    /// Splits off `n - 1` read-only shares of `self` and runs `f` with those shares and a
+    /// [`std::thread::Scope`] to spawn scoped threads with, modeled on [`std::thread::scope`].
+    /// Every share is merged back into `self` before this function returns, so `self` regains
+    /// whole ownership regardless of what `f` does with the shares.
+    ///
+    /// # Examples
+    /// ```
+    /// use frc::Frc;
+    ///
+    /// let mut shared = Frc::new(42);
+    ///
+    /// shared.scope(4, |shares, thread_scope| {
+    ///     for share in shares {
+    ///         thread_scope.spawn(move || assert_eq!(**share, 42));
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(shared.unwrap(), 42);
+    /// ```
+    ///
+    /// # Panics
+    /// If `n` is `0`.
+    pub fn scope<'env, F, R>(&'env mut self, n: usize, f: F) -> R
+    where
+        T: Send + Sync,
+        F: for<'scope> FnOnce(&'scope [Self], &'scope std::thread::Scope<'scope, 'env>) -> R,
+    {
+        assert!(n > 0, "cannot scope into zero shares");
+
+        let shares = self.split_n(n).into_boxed_slice();
+
+        // `f` is quantified `for<'scope>`, so this call has to typecheck as if `'scope` could be
+        // instantiated all the way up to `'env` (the only bound `std::thread::Scope` gives it).
+        // `shares` is local to this call and can never prove that, even though the `'scope` that
+        // `std::thread::scope` actually picks is far shorter. Going through a raw pointer sidesteps
+        // that unprovable bound instead of fighting it; it's sound because the allocation outlives
+        // the whole `std::thread::scope` call below (it's only freed afterwards, once every thread
+        // that borrowed it has joined) and is only ever read through shared references while loaned
+        // out.
+        let shares = Box::into_raw(shares);
+
+        let result = std::thread::scope(|thread_scope| f(unsafe { &*shares }, thread_scope));
+
+        for share in unsafe { Box::from_raw(shares) }.into_vec() {
+            self.merge(share);
+        }
+
+        result
     }
 }
 
@@ -202,6 +363,36 @@ impl<T: ?Sized> From<Box<T>> for Frc<T> {
     }
 }
 
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Frc<U>> for Frc<T> {}
+
+impl<T: Clone> From<&[T]> for Frc<[T]> {
+    fn from(slice: &[T]) -> Self {
+        let boxed: Box<[T]> = slice.into();
+        boxed.into()
+    }
+}
+
+impl From<&str> for Frc<str> {
+    fn from(s: &str) -> Self {
+        let boxed: Box<str> = s.into();
+        boxed.into()
+    }
+}
+
+impl<T> From<Vec<T>> for Frc<[T]> {
+    fn from(v: Vec<T>) -> Self {
+        let boxed: Box<[T]> = v.into_boxed_slice();
+        boxed.into()
+    }
+}
+
+impl From<String> for Frc<str> {
+    fn from(s: String) -> Self {
+        let boxed: Box<str> = s.into_boxed_str();
+        boxed.into()
+    }
+}
+
 impl<T: ?Sized> Deref for Frc<T> {
     type Target = T;
 
@@ -213,24 +404,11 @@ impl<T: ?Sized> Deref for Frc<T> {
 #[derive(Debug)]
 pub struct MergeErr<T: ?Sized> {
     pub other: Frc<T>,
-    kind: MergeErrKind,
-}
-
-#[derive(Debug)]
-enum MergeErrKind {
-    FractionOverflow(FracOverflowInfo),
-    IncompatibleFrcs,
 }
 
-#[derive(Debug)]
-struct FracOverflowInfo(frac::Frac, frac::Frac);
-
 impl<T: ?Sized> Display for MergeErr<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.kind {
-            MergeErrKind::FractionOverflow(FracOverflowInfo(lhs, rhs)) => write!(f, "The inner fractions are too different ({} and {}), and adding them together causes an overflow!", lhs, rhs),
-            MergeErrKind::IncompatibleFrcs => write!(f, "Tried merging two Frcs owning different data!"),
-        }
+        write!(f, "Tried merging two Frcs owning different data!")
     }
 }
 
@@ -238,6 +416,24 @@ impl<T: ?Sized + std::fmt::Debug> std::error::Error for MergeErr<T> {}
 
 #[cfg(test)]
 mod tests {
+    use super::Frc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     #[test]
     fn single_threaded() {}
+
+    #[test]
+    fn scope_merges_shares_back_after_threads_join() {
+        let mut shared = Frc::new(AtomicUsize::new(0));
+
+        shared.scope(8, |shares, thread_scope| {
+            for share in shares {
+                thread_scope.spawn(move || {
+                    share.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(shared.unwrap().into_inner(), 7);
+    }
 }